@@ -0,0 +1,93 @@
+//! embed the build information served on /v1/version
+//! 1. RACCOON_GIT_SHA - the commit the binary was built from, `unknown` outside a git checkout.
+//!    `-dirty` is appended when the working tree has uncommitted changes
+//! 2. RACCOON_BUILD_TIMESTAMP - when this script last ran, in seconds since the Unix epoch.
+//!    SOURCE_DATE_EPOCH is used instead when it is set, for reproducible builds
+//! 3. RACCOON_FEATURES - the enabled cargo features, comma separated
+//! 4. RACCOON_MIGRATION_VERSION - the version of the latest migration in ./migrations
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// run git and return its trimmed output, `None` if git failed or is not installed
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
+
+fn main() {
+    let git_sha = match git(&["rev-parse", "HEAD"]).filter(|sha| !sha.is_empty()) {
+        // don't let git refresh the index, that would make cargo rerun this script on every build
+        Some(sha) => match git(&["--no-optional-locks", "status", "--porcelain"]) {
+            Some(status) if !status.is_empty() => format!("{sha}-dirty"),
+            _ => sha,
+        },
+        None => String::from("unknown"),
+    };
+
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|timestamp| timestamp.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+        });
+
+    // cargo sets CARGO_FEATURE_<NAME> for every enabled feature
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    // migrations are named <version>_<description>.sql, the latest one has the highest version
+    let migration_version = fs::read_dir("migrations")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            file_name.split('_').next()?.parse::<i64>().ok()
+        })
+        .max()
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=RACCOON_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=RACCOON_BUILD_TIMESTAMP={build_timestamp}");
+    println!("cargo:rustc-env=RACCOON_FEATURES={}", features.join(","));
+    println!("cargo:rustc-env=RACCOON_MIGRATION_VERSION={migration_version}");
+
+    // rerun when the sources change so the timestamp and the dirty flag follow the build
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // and when HEAD moves: a checkout changes HEAD, a commit, amend or fast-forward changes the branch ref,
+    // which may only live in packed-refs after git gc
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        let mut git_files = vec![
+            git_dir.join("HEAD"),
+            git_dir.join("index"),
+            git_dir.join("packed-refs"),
+        ];
+        if let Some(branch_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            git_files.push(git_dir.join(branch_ref));
+        }
+        for git_file in git_files.iter().filter(|git_file| git_file.exists()) {
+            println!("cargo:rerun-if-changed={}", git_file.display());
+        }
+    }
+}
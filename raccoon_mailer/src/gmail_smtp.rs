@@ -77,7 +77,7 @@ impl fmt::Display for MailService {
 impl MailService {
     /// construct new email and returns the MailService struct
     /// #Example
-    /// ```rust
+    /// ```rust,ignore
     /// let name = "Opeoluwa";
     /// let address = "Opeolluwa@mailer.com";
     /// let subject = "test email";
@@ -95,7 +95,7 @@ impl MailService {
     }
     /// dispatch the emil
     /// #EXample
-    /// ```rust
+    /// ```rust,ignore
     ///let name = "Opeoluwa";
     /// let address = "Opeolluwa@mailer.com";
     /// let subject = "test email";
@@ -1,9 +1,12 @@
 use crate::models::common::{EmailVerification, OneTimePassword};
 use crate::models::emails::EmailPayload;
-use crate::models::users::{AccountStatus, ResetUserPassword, UserInformation, UserModel};
+use crate::models::users::{
+    AccountStatus, ResetUserPassword, UserInformation, UserModel, UserProfile,
+};
 use crate::utils::api_response::{ApiErrorResponse, ApiSuccessResponse, ValidatedRequest};
 use crate::utils::jwt::JWT_SECRET;
 use crate::utils::jwt::{set_jwt_exp, JwtClaims, JwtPayload};
+use crate::utils::message_catalog::Operation;
 use crate::utils::message_queue::MessageQueue;
 use crate::utils::otp_handler::Otp;
 use crate::utils::sql_query_builder::{Create, Find, FindByPk};
@@ -16,24 +19,22 @@ use time;
 
 const ACCESS_TOKEN_VALIDITY: time::Duration = time::Duration::minutes(10); // the bearer token validity set to 10 minutes
 const REFRESH_TOKEN_VALIDITY: time::Duration = time::Duration::minutes(25); // 25 minutes for refresh token validity
+const READ_ONLY_TOKEN_VALIDITY: time::Duration = time::Duration::hours(24); // read-only tokens for dashboards last a day
 
 /// create new user account
 pub async fn sign_up(
     ValidatedRequest(payload): ValidatedRequest<UserInformation>,
     Extension(database): Extension<PgPool>,
-) -> Result<(StatusCode, Json<ApiSuccessResponse<Value>>), ApiErrorResponse> {
+) -> Result<(StatusCode, ApiSuccessResponse<JwtPayload>), ApiErrorResponse> {
     let new_user = UserModel::create(payload, &database).await;
     if let Err(error_message) = new_user {
-        if error_message.to_string().to_lowercase()
-            == *"no rows returned by a query that expected to return at least one row"
-        {
-            return Err(ApiErrorResponse::ServerError {
+        // the insert does nothing on conflicting emails, so no row is returned
+        if let sqlx::Error::RowNotFound = error_message {
+            return Err(ApiErrorResponse::ConflictError {
                 message: String::from("A user with provided email already exists"),
             });
         }
-        return Err(ApiErrorResponse::ServerError {
-            message: error_message.to_string(),
-        });
+        return Err(error_message.into());
     }
 
     let user = new_user.ok().unwrap();
@@ -54,6 +55,7 @@ pub async fn sign_up(
         email: email.as_ref().unwrap().to_string(),
         fullname: fullname.as_ref().unwrap().to_string(),
         exp: set_jwt_exp(ACCESS_TOKEN_VALIDITY), //set expirations
+        read_only: false,
     };
 
     // build the JWT Token and create a new token
@@ -76,16 +78,10 @@ pub async fn sign_up(
     new_queue.enqueue();
 
     //build the response
-    let response: ApiSuccessResponse<Value> = ApiSuccessResponse::<Value> {
-        success: true,
-        message: String::from("Please verify OTP send to your email to continue"),
-        data: Some(json!({
-            "token":jwt_token,
-            "tokenType":"Bearer".to_string()
-        })),
-    };
+    let response: ApiSuccessResponse<JwtPayload> =
+        ApiSuccessResponse::new(Operation::SignUp, Some(JwtPayload::bearer(jwt_token)));
     //return the response
-    Ok((StatusCode::CREATED, Json(response)))
+    Ok((StatusCode::CREATED, response))
 }
 
 /// to verify email
@@ -97,7 +93,7 @@ pub async fn verify_email(
     ValidatedRequest(payload): ValidatedRequest<OneTimePassword>,
     authenticated_user: JwtClaims,
     Extension(database): Extension<PgPool>,
-) -> Result<(StatusCode, Json<ApiSuccessResponse<Value>>), ApiErrorResponse> {
+) -> Result<(StatusCode, ApiSuccessResponse<Value>), ApiErrorResponse> {
     let user_information = UserModel::find_by_pk(&authenticated_user.id, &database).await;
 
     match user_information {
@@ -121,17 +117,11 @@ pub async fn verify_email(
 
             //update the user account status
             Otp::unlink_from_user(user.id, &database).await;
-            let response_body = ApiSuccessResponse {
-                success: true,
-                message: String::from("User account successfully activated"),
-                data: None,
-            };
+            let response_body = ApiSuccessResponse::new(Operation::VerifyEmail, None);
 
-            Ok((StatusCode::OK, Json(response_body)))
+            Ok((StatusCode::OK, response_body))
         }
-        Err(error_message) => Err(ApiErrorResponse::BadRequest {
-            message: error_message.to_string(),
-        }),
+        Err(error_message) => Err(error_message.into()),
     }
 }
 
@@ -141,17 +131,19 @@ pub async fn verify_email(
 /// at any rate, the token will accept email to return a JWT token to the user
 /// the returned JWT will contain the required information needed by the server for further processing
 /// the server will also send new token to the user's email if the email as found
-
 pub async fn request_new_otp(
     // ValidatedRequest(payload): ValidatedRequest<EmailVerification>,
     authenticated_user: JwtClaims,
     Extension(database): Extension<PgPool>,
-) -> Result<(StatusCode, Json<ApiSuccessResponse<Value>>), ApiErrorResponse> {
+) -> Result<(StatusCode, ApiSuccessResponse<JwtPayload>), ApiErrorResponse> {
     // find the user
     let user_information = UserModel::find_by_pk(&authenticated_user.id, &database).await;
-    if user_information.is_err() {
-        return Err(ApiErrorResponse::BadRequest {
-            message: String::from("A user with the provided email was not found!"),
+    if let Err(error_message) = user_information {
+        return Err(match error_message {
+            sqlx::Error::RowNotFound => ApiErrorResponse::NotFound {
+                message: String::from("A user with the provided email was not found!"),
+            },
+            error_message => error_message.into(),
         });
     }
 
@@ -168,6 +160,7 @@ pub async fn request_new_otp(
         email: email.as_ref().unwrap().to_string(),
         fullname: fullname.as_ref().unwrap().to_string(),
         exp: set_jwt_exp(ACCESS_TOKEN_VALIDITY), //set expirations
+        read_only: false,
     };
 
     // build the JWT Token and create a new token
@@ -190,16 +183,11 @@ pub async fn request_new_otp(
     new_queue.enqueue();
 
     //build the response
-    let response: ApiSuccessResponse<Value> = ApiSuccessResponse::<Value> {
-        success: true,
-        message: String::from("Please verify OTP send to your email to continue"),
-        data: Some(json!({
-            // "user":UserModel { ..updated_user },
-            "token":jwt_token,
-            "tokenType":"Bearer".to_string()
-        })),
-    };
-    Ok((StatusCode::CREATED, Json(response)))
+    let response: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::new(
+        Operation::RequestNewOtp,
+        Some(JwtPayload::bearer(jwt_token)),
+    );
+    Ok((StatusCode::CREATED, response))
 }
 
 /// request verification
@@ -209,18 +197,15 @@ pub async fn request_new_otp(
 pub async fn request_account_verification(
     ValidatedRequest(payload): ValidatedRequest<EmailVerification>,
     Extension(database): Extension<PgPool>,
-) -> Result<(StatusCode, Json<ApiSuccessResponse<Value>>), ApiErrorResponse> {
+) -> Result<(StatusCode, ApiSuccessResponse<JwtPayload>), ApiErrorResponse> {
     // find the user
     let user_information = UserModel::find(json!({"email":payload.email }), &database).await;
-    if user_information.is_err() {
-        return Err(ApiErrorResponse::BadRequest {
-            message: String::from("A user with the provided email was not found!"),
-        });
-    }
-
-    if let Err(err_message) = user_information {
-        return Err(ApiErrorResponse::BadRequest {
-            message: err_message.to_string(), // message: String::from("A user with the provided email was not found!"),
+    if let Err(error_message) = user_information {
+        return Err(match error_message {
+            sqlx::Error::RowNotFound => ApiErrorResponse::NotFound {
+                message: String::from("A user with the provided email was not found!"),
+            },
+            error_message => error_message.into(),
         });
     }
     // generate new otp
@@ -236,6 +221,7 @@ pub async fn request_account_verification(
         email: email.as_ref().unwrap().to_string(),
         fullname: fullname.as_ref().unwrap().to_string(),
         exp: set_jwt_exp(ACCESS_TOKEN_VALIDITY), //set expirations
+        read_only: false,
     };
 
     // build the JWT Token and create a new token
@@ -258,15 +244,11 @@ pub async fn request_account_verification(
     new_queue.enqueue();
 
     //build the response
-    let response: ApiSuccessResponse<Value> = ApiSuccessResponse::<Value> {
-        success: true,
-        message: String::from("Please verify OTP send to your email to continue"),
-        data: Some(json!({
-            "token":jwt_token,
-            "tokenType":"Bearer".to_string()
-        })),
-    };
-    Ok((StatusCode::CREATED, Json(response)))
+    let response: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::new(
+        Operation::RequestAccountVerification,
+        Some(JwtPayload::bearer(jwt_token)),
+    );
+    Ok((StatusCode::CREATED, response))
 }
 
 ///Login a New User :
@@ -276,32 +258,20 @@ pub async fn request_account_verification(
 pub async fn login(
     ValidatedRequest(payload): ValidatedRequest<UserInformation>,
     Extension(database): Extension<PgPool>,
-) -> Result<(StatusCode, Json<ApiSuccessResponse<JwtPayload>>), ApiErrorResponse> {
+) -> Result<(StatusCode, ApiSuccessResponse<JwtPayload>), ApiErrorResponse> {
     let user_information = UserModel::find(json!({"email":payload.email}), &database).await;
     if let Err(error_message) = user_information {
-        return Err(ApiErrorResponse::ServerError {
-            message: error_message.to_string(),
+        // don't reveal whether the email is registered
+        return Err(match error_message {
+            sqlx::Error::RowNotFound => ApiErrorResponse::Unauthorized {
+                message: String::from("Invalid email or password"),
+            },
+            error_message => error_message.into(),
         });
     }
 
     let user = user_information.ok().unwrap();
-    let user_account_status = user.account_status.unwrap();
-
-    //if user account has not been verified
-    if user_account_status == AccountStatus::Inactive {
-        return Err(ApiErrorResponse::Unauthorized {
-            message: String::from(
-                "Your account has not been activated. Please verify your email to continue",
-            ),
-        });
-    }
-
-    // if user account has been deactivated
-    if user_account_status == AccountStatus::Deactivated {
-        return Err(ApiErrorResponse::Unauthorized {
-            message: String::from("Account has been suspended, please contact administrator"),
-        });
-    }
+    ensure_account_is_usable(user.account_status.unwrap())?;
 
     //verify the password
     let is_correct_password: bool = user.verify_pswd_hash(&payload.password.unwrap());
@@ -329,6 +299,7 @@ pub async fn login(
             .unwrap_or(&"default".to_string())
             .to_string(),
         exp: set_jwt_exp(ACCESS_TOKEN_VALIDITY), //set expirations
+        read_only: false,
     };
     //fetch the JWT secret
     /*   let jwt_secret = crate::shared::jwt_schema::jwt_secret(); */
@@ -341,28 +312,22 @@ pub async fn login(
     //build the user jwt token
     let token = encode(&jwt_header, &jwt_payload, &JWT_SECRET.encoding);
     //construct and return a response
-    let response: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::<JwtPayload> {
-        success: true,
-        message: String::from("user successfully logged in"),
-        data: Some(JwtPayload {
-            token: token.unwrap(),
-            token_type: String::from("Bearer"),
-        }),
-    };
+    let response: ApiSuccessResponse<JwtPayload> =
+        ApiSuccessResponse::new(Operation::Login, Some(JwtPayload::bearer(token.unwrap())));
     // response
-    Ok((StatusCode::OK, Json(response)))
+    Ok((StatusCode::OK, response))
 }
 
 /// Get the user profile fom the database.
 /// To do this,
 ///  Get the jwt token fom the header,
 ///  Validate the token then get the user_id from the validated token
-/// - use the user_id to make request to the database
+///  use the user_id to make request to the database
 /// return the user details if no error else return the appropriate error code and response
 pub async fn fetch_user_profile(
     authenticated_user: JwtClaims,
     Extension(database): Extension<PgPool>,
-) -> Result<Json<ApiSuccessResponse<Value>>, ApiErrorResponse> {
+) -> Result<ApiSuccessResponse<UserProfile<UserModel>>, ApiErrorResponse> {
     // Send the protected data to the user
     // fetch the user details from the database using...
     //the user id from the authenticated_user object
@@ -373,36 +338,26 @@ pub async fn fetch_user_profile(
         Ok(user_object) => {
             //build up the response body
             // don't return the value of the user password
-            let response_body: ApiSuccessResponse<Value> = ApiSuccessResponse {
-                success: true,
-                message: "User information successfully fetched".to_string(),
-                data: Some(json!({
-                    "user":UserModel {
-                    password: Some("".to_string()),
-                    ..user_object
-                }
-                })),
-            };
+            let response_body = ApiSuccessResponse::new(
+                Operation::FetchUserProfile,
+                Some(UserProfile { user: user_object }),
+            );
 
-            Ok(Json(response_body))
+            Ok(response_body)
         }
-        Err(error_message) => Err(ApiErrorResponse::BadRequest {
-            message: error_message.to_string(),
-        }),
+        Err(error_message) => Err(error_message.into()),
     }
 }
 
 pub async fn request_password_reset(
     ValidatedRequest(payload): ValidatedRequest<UserInformation>,
     Extension(database): Extension<PgPool>,
-) -> Result<(StatusCode, Json<ApiSuccessResponse<JwtPayload>>), ApiErrorResponse> {
+) -> Result<(StatusCode, ApiSuccessResponse<JwtPayload>), ApiErrorResponse> {
     let user_information = UserModel::find(json!({"email":payload.email}), &database).await;
 
     // check the error
     if let Err(error_message) = user_information {
-        return Err(ApiErrorResponse::ServerError {
-            message: error_message.to_string(),
-        });
+        return Err(error_message.into());
     }
 
     let user = user_information.ok().unwrap();
@@ -423,17 +378,14 @@ pub async fn request_password_reset(
             .unwrap_or(&"default".to_string())
             .to_string(),
         exp: set_jwt_exp(ACCESS_TOKEN_VALIDITY), //set expirations
+        read_only: false,
     };
     let token = jwt_payload.generate_token().unwrap();
-    let response: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::<JwtPayload> {
-        success: true,
-        message: String::from("Please verify OTP sent to yor email"),
-        data: Some(JwtPayload {
-            token,
-            token_type: String::from("Bearer"),
-        }),
-    };
-    Ok((StatusCode::OK, Json(response)))
+    let response: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::new(
+        Operation::RequestPasswordReset,
+        Some(JwtPayload::bearer(token)),
+    );
+    Ok((StatusCode::OK, response))
 }
 /*
  * get the user details from the JWT claims
@@ -447,7 +399,7 @@ pub async fn reset_password(
     Json(payload): Json<ResetUserPassword>,
     authenticated_user: JwtClaims,
     Extension(database): Extension<PgPool>,
-) -> Result<Json<ApiSuccessResponse<()>>, ApiErrorResponse> {
+) -> Result<ApiSuccessResponse<()>, ApiErrorResponse> {
     let user_information =
         UserModel::find(json!({"email":authenticated_user.email.trim()}), &database).await;
     //handle errors
@@ -462,22 +414,16 @@ pub async fn reset_password(
             .bind(Some(new_hashed_password.trim()))
             .bind(Some(authenticated_user.email.trim()))
             .fetch_one(&database)
-            .await
-            .unwrap();
+            .await?;
 
             //build up the response body
             // don't return the value of the user password
-            let response_body: ApiSuccessResponse<_> = ApiSuccessResponse {
-                success: true,
-                message: "User password successfully updated".to_string(),
-                data: None,
-            };
+            let response_body: ApiSuccessResponse<_> =
+                ApiSuccessResponse::new(Operation::ResetPassword, None);
             //return the response
-            Ok(Json(response_body))
+            Ok(response_body)
         }
-        Err(error_message) => Err(ApiErrorResponse::BadRequest {
-            message: error_message.to_string(),
-        }),
+        Err(error_message) => Err(error_message.into()),
     }
 }
 
@@ -492,7 +438,7 @@ pub async fn update_user_profile(
     ValidatedRequest(payload): ValidatedRequest<UserInformation>,
     authenticated_user: JwtClaims,
     Extension(database): Extension<PgPool>,
-) -> Result<Json<ApiSuccessResponse<Value>>, ApiErrorResponse> {
+) -> Result<ApiSuccessResponse<UserProfile<UserInformation>>, ApiErrorResponse> {
     //get the user id from the destructured JWT claims
     //destructure the payload
     let user_information = sqlx::query_as::<_, UserInformation>(
@@ -510,17 +456,14 @@ pub async fn update_user_profile(
         Ok(updated_user) => {
             //build up the response body
             // don't return the value of the user password
-            let response_body: ApiSuccessResponse<Value> = ApiSuccessResponse {
-                success: true,
-                message: "User information successfully updated".to_string(),
-                data: Some(json!({"user": UserInformation { ..updated_user }})),
-            };
+            let response_body = ApiSuccessResponse::new(
+                Operation::UpdateUserProfile,
+                Some(UserProfile { user: updated_user }),
+            );
             //return the response
-            Ok(Json(response_body))
+            Ok(response_body)
         }
-        Err(error_message) => Err(ApiErrorResponse::BadRequest {
-            message: error_message.to_string(),
-        }),
+        Err(error_message) => Err(error_message.into()),
     }
 }
 
@@ -528,7 +471,7 @@ pub async fn update_user_profile(
 pub async fn get_refresh_token(
     authenticated_user: JwtClaims,
     Extension(database): Extension<PgPool>,
-) -> Result<Json<ApiSuccessResponse<JwtPayload>>, ApiErrorResponse> {
+) -> Result<ApiSuccessResponse<JwtPayload>, ApiErrorResponse> {
     // Send the protected data to the user
     // fetch the user details from the database using...
     //the user id from the authenticated_user object
@@ -556,6 +499,8 @@ pub async fn get_refresh_token(
                 email: email.as_ref().unwrap().to_string(),
                 fullname: fullname.as_ref().unwrap().to_string(),
                 exp: set_jwt_exp(REFRESH_TOKEN_VALIDITY), //set expirations
+                // a refreshed read-only token must stay read-only
+                read_only: authenticated_user.read_only,
             };
             //fetch the JWT secret
             /*   let jwt_secret = crate::shared::jwt_schema::jwt_secret(); */
@@ -568,19 +513,72 @@ pub async fn get_refresh_token(
             //build the user jwt token
             let token = encode(&jwt_header, &jwt_payload, &JWT_SECRET.encoding);
             //construct and return a response
-            let response_body: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::<JwtPayload> {
-                success: true,
-                message: String::from("user authorization token successfully updated"),
-                data: Some(JwtPayload {
-                    token: token.unwrap(),
-                    token_type: String::from("Refresh"),
-                }),
-            };
-            Ok(Json(response_body))
+            let response_body: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::new(
+                Operation::RefreshToken,
+                Some(JwtPayload::refresh(token.unwrap())),
+            );
+            Ok(response_body)
         }
-        Err(error_message) => Err(ApiErrorResponse::BadRequest {
-            message: error_message.to_string(),
+        Err(error_message) => Err(error_message.into()),
+    }
+}
+
+/// mint a read-only token
+/// the token carries the same identity as the bearer token used to request it
+/// but can only be used with GET, HEAD and OPTIONS requests, e.g for dashboards and analytics integrations
+/// a read-only token cannot be used to mint another token since this is a POST request
+pub async fn request_read_only_token(
+    authenticated_user: JwtClaims,
+    Extension(database): Extension<PgPool>,
+) -> Result<(StatusCode, ApiSuccessResponse<JwtPayload>), ApiErrorResponse> {
+    let user_information = UserModel::find_by_pk(&authenticated_user.id, &database).await;
+    if let Err(error_message) = user_information {
+        return Err(error_message.into());
+    }
+
+    let user = user_information.ok().unwrap();
+    // the bearer token may predate a deactivation, or be the unverified sign up token
+    ensure_account_is_usable(user.account_status.unwrap_or(AccountStatus::Inactive))?;
+
+    let UserModel {
+        id,
+        email,
+        fullname,
+        ..
+    } = &user;
+    let jwt_payload = JwtClaims {
+        id: id.to_string(),
+        email: email.as_ref().unwrap().to_string(),
+        fullname: fullname
+            .as_ref()
+            .unwrap_or(&"default".to_string())
+            .to_string(),
+        exp: set_jwt_exp(READ_ONLY_TOKEN_VALIDITY), //set expirations
+        read_only: true,
+    };
+    let token = jwt_payload.generate_token().unwrap();
+    let response: ApiSuccessResponse<JwtPayload> = ApiSuccessResponse::new(
+        Operation::CreateReadOnlyToken,
+        Some(JwtPayload::bearer(token)),
+    );
+    Ok((StatusCode::CREATED, response))
+}
+
+/// reject accounts that have not been verified or have been deactivated
+/// used by login and by any handler that mints a token from an existing one
+fn ensure_account_is_usable(account_status: AccountStatus) -> Result<(), ApiErrorResponse> {
+    match account_status {
+        //if user account has not been verified
+        AccountStatus::Inactive => Err(ApiErrorResponse::Unauthorized {
+            message: String::from(
+                "Your account has not been activated. Please verify your email to continue",
+            ),
         }),
+        // if user account has been deactivated
+        AccountStatus::Deactivated => Err(ApiErrorResponse::Unauthorized {
+            message: String::from("Account has been suspended, please contact administrator"),
+        }),
+        _ => Ok(()),
     }
 }
 
@@ -590,3 +588,18 @@ pub async fn get_refresh_token(
 pub async fn _logout() {
     todo!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    #[test]
+    fn only_verified_and_active_accounts_should_get_tokens() {
+        for account_status in [AccountStatus::Inactive, AccountStatus::Deactivated] {
+            let rejection = ensure_account_is_usable(account_status).err().unwrap();
+            assert_eq!(rejection.into_response().status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(ensure_account_is_usable(AccountStatus::Active).is_ok());
+    }
+}
@@ -0,0 +1,29 @@
+use crate::utils::api_response::ApiSuccessResponse;
+use crate::utils::build_info::BuildInformation;
+use crate::utils::message_catalog::Operation;
+use crate::utils::pool_monitor::{PoolMetrics, PoolMetricsSnapshot};
+use axum::Extension;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// database pool health
+/// return the pool size, saturation and the probe waits recorded by the pool monitor
+/// the request itself always succeeds, `healthy` is false if the pool is closed or the last probe failed
+pub async fn database_health(
+    Extension(database): Extension<PgPool>,
+    Extension(pool_metrics): Extension<Arc<PoolMetrics>>,
+) -> ApiSuccessResponse<PoolMetricsSnapshot> {
+    ApiSuccessResponse::new(
+        Operation::FetchDatabaseHealth,
+        Some(pool_metrics.snapshot(&database)),
+    )
+}
+
+/// build information
+/// return the version, git SHA, build time, enabled features and migration level of the running binary
+pub async fn build_information() -> ApiSuccessResponse<BuildInformation> {
+    ApiSuccessResponse::new(
+        Operation::FetchBuildInformation,
+        Some(BuildInformation::current()),
+    )
+}
@@ -1,3 +1,4 @@
 pub mod auth_controllers;
+pub mod health_controllers;
 pub mod oauth2_discord;
 pub mod oauth2_google;
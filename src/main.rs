@@ -1,12 +1,10 @@
 use axum::handler::Handler;
-use axum::response::IntoResponse;
-use axum::{extract::Extension, http::StatusCode, routing::get_service, Router};
+use axum::{extract::Extension, middleware, Router};
 use dotenv::dotenv;
 use raccoon_macros::raccoon_info;
-use sqlx::postgres::PgPoolOptions;
-use std::{env, net::SocketAddr, path::PathBuf};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::{env, net::SocketAddr, sync::Arc};
 use tower_http::cors::{Any, CorsLayer};
-use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -15,57 +13,53 @@ mod models;
 mod routes;
 mod utils;
 
+use utils::api_response::ApiErrorResponse;
+use utils::build_info::BuildInformation;
+use utils::kill_switch::{self, KillSwitches, SharedKillSwitches};
+use utils::pool_monitor::{self, PoolMetrics, PoolMonitorConfig};
+use utils::server_config::ServerTuning;
+
+/// the maximum number of connections in the database pool
+const DATABASE_MAX_CONNECTIONS: u32 = 5;
+
 #[tokio::main]
 async fn main() {
     //the logger implementation
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "logging=debug,tower_http=debug".into()),
+            std::env::var("RUST_LOG")
+                .unwrap_or_else(|_| "raccoon=info,logging=debug,tower_http=debug".into()),
         ))
         .with(tracing_subscriber::fmt::layer())
         .init();
 
     dotenv().ok();
+    BuildInformation::current().log_banner();
     //try parsing database connection string
     //TODO" add graceful shutdown
     let database_connection_string =
         env::var("DATABASE_URL").expect("database URL is not provided in env variable");
     let database = PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(DATABASE_MAX_CONNECTIONS)
         // .connect_timeout(Duration::from_secs(4))
         .connect(&database_connection_string)
         .await
         .expect("Could not connect to database ");
     raccoon_info!("Successfully connected to database");
 
-    //static file mounting
-    let assets_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("views");
-    let static_files_service = get_service(
-        ServeDir::new(assets_dir).append_index_html_on_directories(true),
-    )
-    .handle_error(|error: std::io::Error| async move {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Unhandled internal error: {error}"),
-        )
-    });
+    // watch the pool for starvation, the metrics are served on /v1/health/database
+    let pool_monitor_config = PoolMonitorConfig::from_env();
+    let pool_metrics = Arc::new(PoolMetrics::new(
+        DATABASE_MAX_CONNECTIONS,
+        pool_monitor_config.metrics_window,
+    ));
+    pool_monitor::spawn(database.clone(), pool_metrics.clone(), pool_monitor_config);
 
-    //initialize cors layer
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    //mount the app routes and middleware
-    let app = Router::new()
-        .fallback(static_files_service)
-        .nest("/v1/", routes::root::router())
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .layer(Extension(database));
+    // the kill switches start from DISABLED_ENDPOINTS and follow KILL_SWITCH_FILE if it is set
+    let kill_switches = SharedKillSwitches::new(KillSwitches::from_env());
+    kill_switch::spawn_reloader(kill_switches.clone());
 
-    // add a fallback service for handling routes to unknown paths
-    let app = app.fallback(handle_404.into_service());
+    let app = app(database, pool_metrics, kill_switches);
 
     //mount the server to an ip address
     /*
@@ -87,13 +81,7 @@ async fn main() {
          * if the environment is production, use the derived port and the placeholder address
          * else use the default localhost IP address and a chosen port
          */
-        Ok(env) => {
-            if env == String::from("production").trim() {
-                SocketAddr::from(([0, 0, 0, 0], port))
-            } else {
-                SocketAddr::from(([127, 0, 0, 1], port))
-            }
-        }
+        Ok(env) if env.trim() == "production" => SocketAddr::from(([0, 0, 0, 0], port)),
 
         _ =>
         // return the localhost IP address as a fall through
@@ -104,24 +92,165 @@ async fn main() {
     };
     //launch the server
     println!("Ignition started on http://{}", &ip_address);
-    axum::Server::bind(&ip_address)
+    ServerTuning::from_env()
+        .bind(&ip_address)
+        .expect("Could not bind the server address")
         .serve(app.into_make_service())
         .await
         .unwrap();
 }
 
+/// mount the app routes and middleware
+/// the 404 fallback is set before the layers so unknown paths go through the same middleware as the routes
+fn app(
+    database: PgPool,
+    pool_metrics: Arc<PoolMetrics>,
+    kill_switches: SharedKillSwitches,
+) -> Router {
+    //initialize cors layer
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any);
+
+    // add a fallback service for handling routes to unknown paths
+    let app = Router::new()
+        .nest("/v1/", routes::root::router())
+        .fallback(handle_404.into_service());
+    // faults are injected inside the other middleware so injected errors are still traced and negotiated
+    #[cfg(feature = "chaos")]
+    let app = app.layer(middleware::from_fn(utils::chaos::inject_faults));
+    app.layer(middleware::from_fn(kill_switch::reject_disabled_endpoints))
+        .layer(cors)
+        // outside the CORS layer, which would otherwise replace the `Vary` header set here
+        .layer(middleware::from_fn(utils::api_response::negotiate_envelope))
+        .layer(TraceLayer::new_for_http())
+        .layer(Extension(database))
+        .layer(Extension(pool_metrics))
+        .layer(Extension(kill_switches))
+}
+
 // 404 handler
 
 // async fn handler() -> axum::response::Html<&'static str> {
 //     axum::response::Html("<h1>Hello, World!</h1>")
 // }
 
-async fn handle_404() -> impl IntoResponse {
-    (
-        StatusCode::NOT_FOUND,
-        axum::response::Json(serde_json::json!({
-        "success":false,
-        "message":String::from("The requested resource does not exist on this server!"),
-        })),
-    )
+async fn handle_404() -> ApiErrorResponse {
+    ApiErrorResponse::NotFound {
+        message: String::from("The requested resource does not exist on this server!"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use tower::ServiceExt;
+
+    fn test_app() -> Router {
+        test_app_with_metrics(PoolMetrics::default())
+    }
+
+    fn test_app_with_metrics(pool_metrics: PoolMetrics) -> Router {
+        let database = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/raccoon")
+            .unwrap();
+        app(
+            database,
+            Arc::new(pool_metrics),
+            SharedKillSwitches::default(),
+        )
+    }
+
+    fn unhealthy_pool_metrics() -> PoolMetrics {
+        let pool_metrics = PoolMetrics::default();
+        pool_metrics.record_probe_failure();
+        pool_metrics
+    }
+
+    /// the envelope header is listed next to the `Vary` values added by the CORS layer
+    fn varies_on_envelope(response: &axum::response::Response) -> bool {
+        response
+            .headers()
+            .get_all(header::VARY)
+            .iter()
+            .any(|value| value == utils::api_response::RESPONSE_ENVELOPE_HEADER)
+    }
+
+    #[tokio::test]
+    async fn unknown_paths_should_honour_raw_mode() {
+        let request = Request::builder()
+            .uri("/v1/unknown")
+            .header(utils::api_response::RESPONSE_ENVELOPE_HEADER, "raw")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_app().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        assert!(varies_on_envelope(&response));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            problem["detail"],
+            "The requested resource does not exist on this server!"
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_paths_should_be_enveloped_by_default() {
+        let request = Request::builder()
+            .uri("/unknown")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_app().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(varies_on_envelope(&response));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope["success"], false);
+    }
+
+    #[tokio::test]
+    async fn unhealthy_pool_should_be_reported_in_the_envelope() {
+        let request = Request::builder()
+            .uri("/v1/health/database")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_app_with_metrics(unhealthy_pool_metrics())
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope["success"], true);
+        assert_eq!(envelope["data"]["healthy"], false);
+        assert_eq!(envelope["data"]["probeFailures"], 1);
+    }
+
+    #[tokio::test]
+    async fn unhealthy_pool_should_keep_the_metrics_in_raw_mode() {
+        let request = Request::builder()
+            .uri("/v1/health/database?envelope=raw")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_app_with_metrics(unhealthy_pool_metrics())
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let snapshot: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(snapshot["healthy"], false);
+        assert_eq!(snapshot["probeFailures"], 1);
+    }
 }
@@ -4,6 +4,7 @@ use validator::Validate;
 //TOD: add validation
 ///one time password
 #[derive(Debug, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
 pub struct OneTimePassword {
     /// the token itself
     pub token: String,
@@ -11,6 +12,7 @@ pub struct OneTimePassword {
 
 /// user email for requesting account verification
 #[derive(Debug, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
 pub struct EmailVerification {
     pub email: String,
 }
@@ -57,8 +57,15 @@ pub struct UserModel {
     pub last_available_at: Option<NaiveDateTime>,
 }
 
+/// the user profile response, a typed wrapper so the user is serialized without building a JSON value first
+#[derive(Debug, Serialize)]
+pub struct UserProfile<User> {
+    pub user: User,
+}
+
 ///the user information is derived from the user model
 /// it shall be responsible for providing the user information such as in JWT encryption
+/// fields are serialized in camelCase, the snake_case aliases keep older clients working
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct UserInformation {
@@ -69,15 +76,21 @@ pub struct UserInformation {
     pub fullname: Option<String>,
     pub username: Option<String>,
     pub email: Option<String>,
+    #[serde(alias = "account_status")]
     pub account_status: Option<AccountStatus>,
+    #[serde(alias = "date_of_birth")]
     pub date_of_birth: Option<NaiveDate>,
     pub gender: Option<UserGender>,
     pub avatar: Option<String>,
+    #[serde(alias = "phone_number")]
     pub phone_number: Option<String>,
     #[serde(skip_serializing)]
     pub password: Option<String>,
+    #[serde(alias = "created_at")]
     pub created_at: Option<NaiveDateTime>,
+    #[serde(alias = "updated_at")]
     pub updated_at: Option<NaiveDateTime>,
+    #[serde(alias = "last_available_at")]
     pub last_available_at: Option<NaiveDateTime>,
 }
 
@@ -218,6 +231,8 @@ impl Find for UserModel {
 ///user authorization information
 /// to be used for making login and sign up requests
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Validate)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct UserAuthCredentials {
     #[validate(email)]
     pub email: String,
@@ -240,6 +255,49 @@ impl Default for UserGender {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResetUserPassword {
+    #[serde(alias = "new_password")]
     pub new_password: String,
+    #[serde(alias = "confirm_password")]
     pub confirm_password: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_password_payload_should_accept_camel_and_snake_case() {
+        let camel_case: ResetUserPassword =
+            serde_json::from_str(r#"{"newPassword":"secret","confirmPassword":"secret"}"#).unwrap();
+        let snake_case: ResetUserPassword =
+            serde_json::from_str(r#"{"new_password":"secret","confirm_password":"secret"}"#)
+                .unwrap();
+
+        assert_eq!(camel_case.new_password, snake_case.new_password);
+        assert_eq!(camel_case.confirm_password, snake_case.confirm_password);
+    }
+
+    #[test]
+    fn user_information_should_serialize_as_camel_case() {
+        let user_information: UserInformation =
+            serde_json::from_str(r#"{"phone_number":"0800","dateOfBirth":"2000-01-01"}"#).unwrap();
+        let serialized = serde_json::to_value(&user_information).unwrap();
+
+        assert_eq!(serialized["phoneNumber"], "0800");
+        assert_eq!(serialized["dateOfBirth"], "2000-01-01");
+        assert!(serialized.get("phone_number").is_none());
+    }
+
+    #[test]
+    fn user_profile_should_not_serialize_the_password() {
+        let user: UserModel = serde_json::from_str(&format!(
+            r#"{{"id":"{}","email":"jane@example.com","password":"hash"}}"#,
+            Uuid::nil()
+        ))
+        .unwrap();
+        let serialized = serde_json::to_value(UserProfile { user }).unwrap();
+
+        assert_eq!(serialized["user"]["email"], "jane@example.com");
+        assert!(serialized["user"].get("password").is_none());
+    }
+}
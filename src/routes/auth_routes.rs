@@ -25,4 +25,5 @@ pub fn routes() -> axum::Router {
         .route("/me", get(handler::fetch_user_profile))
         .route("/me", put(handler::update_user_profile))
         .route("/", get(handler::get_refresh_token))
+        .route("/read-only-token", post(handler::request_read_only_token))
 }
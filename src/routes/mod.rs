@@ -1,5 +1,7 @@
 pub mod auth_routes;
+pub mod health_routes;
 pub mod root;
 pub mod oauth2;
 pub mod oauth2_discord;
-pub mod oauth2_google;
\ No newline at end of file
+pub mod oauth2_google;
+pub mod version_routes;
\ No newline at end of file
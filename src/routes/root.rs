@@ -1,4 +1,4 @@
-use super::{auth_routes, oauth2};
+use super::{auth_routes, health_routes, oauth2, version_routes};
 use axum::Router;
 
 /**
@@ -9,4 +9,6 @@ pub fn router() -> axum::Router {
     Router::new()
         .nest("/auth", auth_routes::routes())
         .nest("/oauth2", oauth2::routes())
+        .nest("/health", health_routes::routes())
+        .nest("/version", version_routes::routes())
 }
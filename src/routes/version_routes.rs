@@ -0,0 +1,8 @@
+//! #build information routes
+use crate::controllers::health_controllers as handler;
+use axum::{routing::get, Router};
+
+// mount the controllers to the route
+pub fn routes() -> axum::Router {
+    Router::new().route("/", get(handler::build_information))
+}
@@ -13,17 +13,24 @@
 // limitations under the License.
 
 use async_trait::async_trait;
+use axum::body::{boxed, Full};
 use axum::extract::FromRequest;
 use axum::extract::RequestParts;
-use axum::http::StatusCode;
+use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode, Uri};
+use axum::middleware::Next;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::BoxError;
 use axum::Json;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::borrow::Cow;
 use thiserror::Error;
 use validator::Validate;
+
+use crate::utils::message_catalog::Operation;
+use crate::utils::pool_monitor;
 /// the API response is supposed to be an enum of two variants
 /// ApiResponse::Success<D:Data> and ApiResponse::Error<E:Error>
 ///
@@ -39,6 +46,9 @@ use validator::Validate;
 /// let success :ApiResponse<Data, _>
 /// let error : ApiResponse<_, Error>
 /// let neither_data_nor_error : ApiResponse<_,_>
+///
+/// response and model types are serialized in camelCase using `#[serde(rename_all = "camelCase")]`
+/// request types with multi-word fields also accept the snake_case spelling through `#[serde(alias = "...")]`
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiResponse<Data> {
@@ -51,14 +61,41 @@ pub struct ApiResponse<Data> {
 ///Api success response
 /// the api success response returns succes
 /// accepts message and data from handle/controller
+/// the message and its code come from the message catalog, see [`Operation`]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiSuccessResponse<Data> {
     pub success: bool,
-    pub message: String,
+    pub message: Cow<'static, str>,
+    pub code: Cow<'static, str>,
     pub data: Option<Data>,
 }
 
+impl<Data> ApiSuccessResponse<Data> {
+    /// build a success response with the catalog message of the operation
+    pub fn new(operation: Operation, data: Option<Data>) -> Self {
+        Self {
+            success: true,
+            message: Cow::Borrowed(operation.message()),
+            code: Cow::Borrowed(operation.code()),
+            data,
+        }
+    }
+}
+
+/// marks a response built from `ApiSuccessResponse` or `ApiErrorResponse`
+/// [`negotiate_envelope`] only unwraps marked responses, other JSON bodies are sent as they are
+#[derive(Debug, Clone, Copy)]
+pub struct Enveloped;
+
+impl<Data: Serialize> IntoResponse for ApiSuccessResponse<Data> {
+    fn into_response(self) -> Response {
+        let mut response = Json(self).into_response();
+        response.extensions_mut().insert(Enveloped);
+        response
+    }
+}
+
 /// the error content should be returned as an error of string
 #[allow(dead_code)]
 pub enum ApiErrorResponse {
@@ -76,6 +113,12 @@ pub enum ApiErrorResponse {
     NotFound { message: String },
     /// authorization error
     Unauthorized { message: String },
+    /// authenticated but not allowed to perform the action e.g read-only token
+    Forbidden { message: String },
+    /// the request refers to a record that does not exist e.g a foreign key violation
+    UnprocessableEntity { message: String },
+    /// the endpoint is temporarily unavailable e.g disabled by a kill switch
+    ServiceUnavailable { message: String },
 }
 
 ///implement into response trait for API error
@@ -98,6 +141,13 @@ impl IntoResponse for ApiErrorResponse {
             ApiErrorResponse::ConflictError { message } => (StatusCode::CONFLICT, message),
             //not found error
             ApiErrorResponse::NotFound { message } => (StatusCode::NOT_FOUND, message),
+            ApiErrorResponse::Forbidden { message } => (StatusCode::FORBIDDEN, message),
+            ApiErrorResponse::UnprocessableEntity { message } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, message)
+            }
+            ApiErrorResponse::ServiceUnavailable { message } => {
+                (StatusCode::SERVICE_UNAVAILABLE, message)
+            }
         };
         //build the response body using the ApiResponse struct
         let response_body: ApiResponse<String> = ApiResponse::<String> {
@@ -107,10 +157,58 @@ impl IntoResponse for ApiErrorResponse {
         };
 
         //build up the response status code and the response content
-        (status_code, Json(response_body)).into_response()
+        let mut response = (status_code, Json(response_body)).into_response();
+        response.extensions_mut().insert(Enveloped);
+        response
+    }
+}
+
+/// map database errors to API errors in one place
+/// - a missing row is a 404
+/// - a unique constraint violation is a 409
+/// - a foreign key violation is a 422
+/// - anything else is a 500, the underlying error is logged but not sent to the client
+///
+/// # Example
+/// ```rust
+/// let user = UserModel::find_by_pk(&authenticated_user.id, &database).await?;
+/// ```
+impl From<sqlx::Error> for ApiErrorResponse {
+    fn from(error: sqlx::Error) -> Self {
+        if let sqlx::Error::PoolTimedOut = error {
+            pool_monitor::record_checkout_timeout();
+        }
+        if let sqlx::Error::RowNotFound = error {
+            return ApiErrorResponse::NotFound {
+                message: String::from("The requested resource was not found"),
+            };
+        }
+
+        let sql_state = error
+            .as_database_error()
+            .and_then(|database_error| database_error.code())
+            .map(|code| code.to_string());
+        match sql_state.as_deref() {
+            Some(UNIQUE_VIOLATION) => ApiErrorResponse::ConflictError {
+                message: String::from("A record with the provided details already exists"),
+            },
+            Some(FOREIGN_KEY_VIOLATION) => ApiErrorResponse::UnprocessableEntity {
+                message: String::from("The request refers to a record that does not exist"),
+            },
+            _ => {
+                tracing::error!("database error: {error}");
+                ApiErrorResponse::ServerError {
+                    message: String::from("An internal error occurred, please try again later"),
+                }
+            }
+        }
     }
 }
 
+// PostgreSQL error codes, see https://www.postgresql.org/docs/current/errcodes-appendix.html
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
 ///  a trait to return the field of the structs as an array of strings
 ///  the implementation on user information will return the user is, firstname, username ...
 /// on the user authentication struct, the implementation will return the user email and password
@@ -135,6 +233,7 @@ impl IntoResponse for ApiErrorResponse {
 ///
 /// // do something with the response
 /// ```
+#[allow(dead_code)]
 pub trait EnumerateFields {
     fn collect_as_strings(&self) -> std::collections::HashMap<String, String>;
 }
@@ -145,10 +244,12 @@ pub trait EnumerateFields {
 /// the no of rows represents the number of items to return for the query, defaults to 10
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct Pagination {
     /// the page number. It maps to `current page number` on the user interface
     pub page: i32,
     /// the number of items to
+    #[serde(alias = "no_of_rows")]
     pub no_of_rows: i32,
 }
 
@@ -252,3 +353,297 @@ impl IntoResponse for RequestError {
         .into_response()
     }
 }
+
+/// the header a client sends to opt out of the `{success, message, data}` envelope
+/// e.g `X-Response-Envelope: raw`. The query parameter `?envelope=raw` does the same
+pub const RESPONSE_ENVELOPE_HEADER: &str = "x-response-envelope";
+
+/// response envelope opt-out (raw mode)
+/// the middleware is mounted once on the router so handlers keep returning `ApiSuccessResponse` and `ApiErrorResponse`
+/// when the client has asked for raw mode, the JSON envelope is unwrapped:
+/// - a success response returns `data` as the body, or `204 No Content` when there is no data and the status is `200`
+/// - an error response returns an `application/problem+json` body with the same status code
+///
+/// # Example
+/// ```rust
+/// let app = Router::new()
+///     .nest("/v1/", routes::root::router())
+///     .layer(axum::middleware::from_fn(api_response::negotiate_envelope));
+/// ```
+/// every response gets `Vary: x-response-envelope` so shared caches keep the two shapes apart
+pub async fn negotiate_envelope<B>(req: Request<B>, next: Next<B>) -> Response {
+    let is_raw_mode = wants_raw_response(req.headers(), req.uri());
+    let response = next.run(req).await;
+
+    let is_enveloped = response.extensions().get::<Enveloped>().is_some();
+    let mut response = if is_raw_mode && is_enveloped {
+        unwrap_envelope(response).await
+    } else {
+        response
+    };
+    response.headers_mut().append(
+        header::VARY,
+        HeaderValue::from_static(RESPONSE_ENVELOPE_HEADER),
+    );
+    response
+}
+
+/// replace the envelope of the response with the raw body
+async fn unwrap_envelope(response: Response) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return ApiErrorResponse::ServerError {
+                message: String::from("Unable to read the response body"),
+            }
+            .into_response()
+        }
+    };
+    let envelope: Value = match serde_json::from_slice(&bytes) {
+        Ok(envelope) => envelope,
+        Err(_) => return Response::from_parts(parts, boxed(Full::from(bytes))),
+    };
+
+    let (status_code, raw_body) = strip_envelope(parts.status, envelope);
+    parts.status = status_code;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    match raw_body {
+        Some(raw_body) => {
+            if !status_code.is_success() {
+                parts.headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/problem+json"),
+                );
+            }
+            Response::from_parts(parts, boxed(Full::from(raw_body.to_string())))
+        }
+        None => {
+            parts.headers.remove(header::CONTENT_TYPE);
+            Response::from_parts(parts, boxed(Full::from("")))
+        }
+    }
+}
+
+/// the client may ask for raw mode through the header or the query parameter
+fn wants_raw_response(headers: &HeaderMap, uri: &Uri) -> bool {
+    let from_header = headers
+        .get(RESPONSE_ENVELOPE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().eq_ignore_ascii_case("raw"))
+        .unwrap_or(false);
+    let from_query = uri
+        .query()
+        .map(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .any(|(key, value)| key == "envelope" && value.eq_ignore_ascii_case("raw"))
+        })
+        .unwrap_or(false);
+
+    from_header || from_query
+}
+
+/// unwrap an `ApiResponse` shaped body
+/// returns the status code and the raw body to send, `None` for an empty body
+fn strip_envelope(status_code: StatusCode, envelope: Value) -> (StatusCode, Option<Value>) {
+    if !status_code.is_success() {
+        let problem = json!({
+            "title": status_code.canonical_reason().unwrap_or_default(),
+            "status": status_code.as_u16(),
+            "detail": envelope["message"],
+        });
+        return (status_code, Some(problem));
+    }
+
+    match envelope.get("data") {
+        Some(data) if !data.is_null() => (status_code, Some(data.clone())),
+        _ if status_code == StatusCode::OK => (StatusCode::NO_CONTENT, None),
+        _ => (status_code, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::users::{UserModel, UserProfile};
+    use crate::utils::alloc_counter::count_allocations;
+    use crate::utils::jwt::JwtPayload;
+
+    #[test]
+    fn missing_rows_should_map_to_not_found() {
+        let response = ApiErrorResponse::from(sqlx::Error::RowNotFound).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// a database error carrying only a SQLSTATE code
+    #[derive(Debug, Error)]
+    #[error("database error {code}")]
+    struct SqlStateError {
+        code: &'static str,
+    }
+
+    impl sqlx::error::DatabaseError for SqlStateError {
+        fn message(&self) -> &str {
+            "database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+    }
+
+    fn database_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(SqlStateError { code }))
+    }
+
+    #[test]
+    fn unique_violations_should_map_to_conflict() {
+        let response = ApiErrorResponse::from(database_error(UNIQUE_VIOLATION)).into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn foreign_key_violations_should_map_to_unprocessable_entity() {
+        let response =
+            ApiErrorResponse::from(database_error(FOREIGN_KEY_VIOLATION)).into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn other_database_errors_should_map_to_server_error() {
+        let response = ApiErrorResponse::from(sqlx::Error::PoolTimedOut).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn checkout_timeouts_should_be_counted() {
+        let database = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/raccoon")
+            .unwrap();
+        let pool_metrics = pool_monitor::PoolMetrics::default();
+        let before = pool_metrics.snapshot(&database).checkout_timeouts;
+
+        let response = ApiErrorResponse::from(sqlx::Error::PoolTimedOut).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        // the counter is process wide, other tests may add to it too
+        assert!(pool_metrics.snapshot(&database).checkout_timeouts > before);
+    }
+
+    #[test]
+    fn success_response_should_borrow_the_catalog_message() {
+        let response = ApiSuccessResponse::<()>::new(Operation::Login, None);
+        assert!(matches!(response.message, Cow::Borrowed(_)));
+        assert!(matches!(response.code, Cow::Borrowed(_)));
+
+        let serialized = serde_json::to_value(&response).unwrap();
+        assert_eq!(serialized["message"], Operation::Login.message());
+        assert_eq!(serialized["code"], Operation::Login.code());
+    }
+
+    #[test]
+    fn pagination_should_accept_camel_and_snake_case() {
+        let camel_case: Pagination = serde_json::from_str(r#"{"page":2,"noOfRows":20}"#).unwrap();
+        let snake_case: Pagination = serde_json::from_str(r#"{"page":2,"no_of_rows":20}"#).unwrap();
+        let serialized = serde_json::to_value(&snake_case).unwrap();
+
+        assert_eq!(camel_case.no_of_rows, 20);
+        assert_eq!(snake_case.no_of_rows, 20);
+        assert_eq!(serialized["noOfRows"], 20);
+        assert!(serialized.get("no_of_rows").is_none());
+    }
+
+    fn sample_user() -> UserModel {
+        serde_json::from_str(&format!(
+            r#"{{"id":"{}","email":"jane@example.com","fullname":"Jane Doe","createdAt":"2023-02-13T18:08:00"}}"#,
+            uuid::Uuid::nil()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn allocations_should_be_counted() {
+        let (_, allocations) = count_allocations(|| String::from("allocated"));
+        assert_eq!(allocations, 1);
+    }
+
+    #[test]
+    fn token_response_should_not_allocate_outside_the_body() {
+        let token = String::from("header.payload.signature");
+        let mut body = Vec::with_capacity(1024);
+
+        let (_, allocations) = count_allocations(|| {
+            let response =
+                ApiSuccessResponse::new(Operation::Login, Some(JwtPayload::bearer(token)));
+            serde_json::to_writer(&mut body, &response).unwrap();
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn profile_response_should_not_allocate_outside_the_body() {
+        let user = sample_user();
+        let mut body = Vec::with_capacity(1024);
+
+        let (_, allocations) = count_allocations(|| {
+            let response =
+                ApiSuccessResponse::new(Operation::FetchUserProfile, Some(UserProfile { user }));
+            serde_json::to_writer(&mut body, &response).unwrap();
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn raw_mode_should_be_negotiated_from_header_or_query() {
+        let mut headers = HeaderMap::new();
+        let uri: Uri = "/v1/auth/me".parse().unwrap();
+        assert!(!wants_raw_response(&headers, &uri));
+
+        headers.insert(RESPONSE_ENVELOPE_HEADER, HeaderValue::from_static("Raw"));
+        assert!(wants_raw_response(&headers, &uri));
+
+        let uri: Uri = "/v1/auth/me?envelope=raw".parse().unwrap();
+        assert!(wants_raw_response(&HeaderMap::new(), &uri));
+    }
+
+    #[test]
+    fn strip_envelope_should_return_data_on_success() {
+        let envelope = json!({"success": true, "message": "ok", "data": {"token": "abc"}});
+        let (status_code, body) = strip_envelope(StatusCode::CREATED, envelope);
+
+        assert_eq!(status_code, StatusCode::CREATED);
+        assert_eq!(body, Some(json!({"token": "abc"})));
+    }
+
+    #[test]
+    fn strip_envelope_should_return_no_content_without_data() {
+        let envelope = json!({"success": true, "message": "ok", "data": null});
+        let (status_code, body) = strip_envelope(StatusCode::OK, envelope);
+
+        assert_eq!(status_code, StatusCode::NO_CONTENT);
+        assert_eq!(body, None);
+    }
+
+    #[test]
+    fn strip_envelope_should_return_problem_details_on_error() {
+        let envelope = json!({"success": false, "message": "not found", "data": null});
+        let (status_code, body) = strip_envelope(StatusCode::NOT_FOUND, envelope);
+
+        assert_eq!(status_code, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            Some(json!({"title": "Not Found", "status": 404, "detail": "not found"}))
+        );
+    }
+}
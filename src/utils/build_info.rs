@@ -0,0 +1,75 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// what was built and deployed, the values are embedded by build.rs at compile time
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInformation {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build_timestamp: Option<DateTime<Utc>>,
+    pub features: Vec<&'static str>,
+    /// the version of the latest migration the binary was built with
+    pub migration_version: i64,
+}
+
+impl BuildInformation {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("RACCOON_GIT_SHA"),
+            build_timestamp: env!("RACCOON_BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)),
+            features: env!("RACCOON_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .collect(),
+            migration_version: env!("RACCOON_MIGRATION_VERSION")
+                .parse()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// log the build information once on startup so it shows up with the first log lines
+    pub fn log_banner(&self) {
+        tracing::info!(
+            version = self.version,
+            git_sha = self.git_sha,
+            build_timestamp = ?self.build_timestamp,
+            features = ?self.features,
+            migration_version = self.migration_version,
+            "starting raccoon"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_information_should_be_embedded() {
+        let build_information = BuildInformation::current();
+
+        assert_eq!(build_information.version, env!("CARGO_PKG_VERSION"));
+        assert!(!build_information.git_sha.is_empty());
+        assert!(build_information.build_timestamp.is_some());
+        assert!(build_information.migration_version > 0);
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! fault injection for exercising client retries and circuit breakers in staging
+//! the module is only compiled with `--features chaos`, never enable it in production
+
+use axum::body::{boxed, Bytes, StreamBody};
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use once_cell::sync::Lazy;
+use std::io;
+use std::time::Duration;
+
+use crate::utils::api_response::ApiErrorResponse;
+use crate::utils::env_config::{covers_path, parse_prefix_list, read_env};
+
+/// CHAOS_ROUTES is parsed when the first request comes in, changing the faults needs a restart
+static CHAOS_RULES: Lazy<ChaosRules> = Lazy::new(ChaosRules::from_env);
+
+/// the faults injected into the requests to a route
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Faults {
+    /// added before the request is handled
+    pub latency: Duration,
+    /// the share of requests answered with a 500, from 0 to 1
+    pub error_rate: f64,
+    /// the share of requests whose connection is dropped mid response, from 0 to 1
+    pub drop_rate: f64,
+}
+
+/// the fault rules, read from CHAOS_ROUTES
+/// a comma separated list of path prefixes, each followed by `=` and `;` separated faults e.g
+/// CHAOS_ROUTES=/v1/auth/login=latency_ms:800;error_rate:0.2,/v1/health=drop_rate:0.5
+#[derive(Debug, Default, PartialEq)]
+pub struct ChaosRules {
+    routes: Vec<(String, Faults)>,
+}
+
+impl ChaosRules {
+    /// read the rules from the environment, no fault is injected if the variable is not set
+    pub fn from_env() -> Self {
+        let chaos_rules = Self::parse(&read_env::<String>("CHAOS_ROUTES").unwrap_or_default());
+        if !chaos_rules.routes.is_empty() {
+            tracing::warn!("fault injection is enabled for {:?}", chaos_rules.routes);
+        }
+        chaos_rules
+    }
+
+    /// routes without faults after `=` are skipped, see [`parse_prefix_list`] for the list format
+    fn parse(value: &str) -> Self {
+        let routes = parse_prefix_list(value)
+            .into_iter()
+            .filter_map(|(path, faults)| Some((path, parse_faults(&faults?))))
+            .collect();
+
+        Self { routes }
+    }
+
+    /// the faults of the first rule covering the path
+    fn find(&self, path: &str) -> Option<Faults> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| covers_path(prefix, path))
+            .map(|(_, faults)| *faults)
+    }
+}
+
+/// parse the `;` separated faults of a route e.g `latency_ms:800;error_rate:0.2`
+fn parse_faults(faults: &str) -> Faults {
+    let mut route_faults = Faults::default();
+    for (name, value) in faults.split(';').filter_map(|fault| fault.split_once(':')) {
+        let value = value.trim();
+        match name.trim() {
+            "latency_ms" => {
+                route_faults.latency = Duration::from_millis(value.parse().unwrap_or_default())
+            }
+            "error_rate" => route_faults.error_rate = parse_rate(value),
+            "drop_rate" => route_faults.drop_rate = parse_rate(value),
+            unknown => tracing::warn!("unknown fault {unknown} in CHAOS_ROUTES"),
+        }
+    }
+    route_faults
+}
+
+/// a rate outside 0 to 1 is clamped, an invalid rate disables the fault
+fn parse_rate(value: &str) -> f64 {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite())
+        .map(|rate| rate.clamp(0.0, 1.0))
+        .unwrap_or_default()
+}
+
+/// inject the configured latency, errors and dropped connections
+/// the error and drop rates do not overlap, a route with 0.2 and 0.3 fails half of the requests
+pub async fn inject_faults<B>(req: Request<B>, next: Next<B>) -> Response {
+    let faults = match CHAOS_RULES.find(req.uri().path()) {
+        Some(faults) => faults,
+        None => return next.run(req).await,
+    };
+
+    if !faults.latency.is_zero() {
+        tokio::time::sleep(faults.latency).await;
+    }
+
+    let roll: f64 = rand::random();
+    if roll < faults.error_rate {
+        return ApiErrorResponse::ServerError {
+            message: String::from("Injected fault, the request was not handled"),
+        }
+        .into_response();
+    }
+    if roll < faults.error_rate + faults.drop_rate {
+        return dropped_connection();
+    }
+
+    next.run(req).await
+}
+
+/// a response whose body fails, hyper aborts the connection once the headers are sent
+fn dropped_connection() -> Response {
+    let body = StreamBody::new(futures::stream::once(async {
+        Err::<Bytes, io::Error>(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "injected dropped connection",
+        ))
+    }));
+    Response::new(boxed(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chaos_rules_should_parse_faults_per_route() {
+        let chaos_rules = ChaosRules::parse(
+            "/v1/auth/login/=latency_ms:800; error_rate:0.2,/v1/health=drop_rate:7,/v1/none",
+        );
+
+        assert_eq!(
+            chaos_rules.find("/v1/auth/login"),
+            Some(Faults {
+                latency: Duration::from_millis(800),
+                error_rate: 0.2,
+                drop_rate: 0.0,
+            })
+        );
+        assert_eq!(
+            chaos_rules
+                .find("/v1/health/database")
+                .map(|faults| faults.drop_rate),
+            Some(1.0)
+        );
+        assert_eq!(chaos_rules.find("/v1/none"), None);
+        assert_eq!(chaos_rules.find("/v1/auth/me"), None);
+    }
+
+    #[test]
+    fn invalid_rates_should_disable_the_fault() {
+        assert_eq!(parse_rate("often"), 0.0);
+        assert_eq!(parse_rate("NaN"), 0.0);
+        assert_eq!(parse_rate("-1"), 0.0);
+        assert_eq!(parse_rate("0.5"), 0.5);
+    }
+}
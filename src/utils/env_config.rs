@@ -0,0 +1,125 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! helpers for reading the runtime configuration from environment variables
+
+use std::env;
+use std::str::FromStr;
+
+/// read and parse an environment variable
+/// `None` if it is missing, blank or cannot be parsed, so callers can fall back to a default e.g
+/// `read_env("TCP_BACKLOG").unwrap_or(1024)`
+///
+/// a value that cannot be parsed is logged so a typo does not silently fall back to the default
+pub fn read_env<T: FromStr>(key: &str) -> Option<T> {
+    read_env_with(key, |value| value.parse().ok())
+}
+
+/// read a boolean environment variable, see [`parse_flag`] for the accepted spellings
+pub fn read_env_flag(key: &str) -> Option<bool> {
+    read_env_with(key, parse_flag)
+}
+
+/// `true`, `1`, `yes` and `on` are true, `false`, `0`, `no` and `off` are false, in any case
+pub fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn read_env_with<T>(key: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+    let value_from_env = env::var(key).ok()?;
+    let value_from_env = value_from_env.trim();
+    if value_from_env.is_empty() {
+        return None;
+    }
+
+    let parsed = parse(value_from_env);
+    if parsed.is_none() {
+        tracing::warn!("ignoring {key}={value_from_env:?}, it is not a valid value");
+    }
+    parsed
+}
+
+/// parse a list of path prefixes, each one optionally followed by `=` and a spec e.g
+/// `/v1/auth/sign-up=sign up is paused,/v1/oauth2`
+/// - entries are separated by commas or new lines
+/// - trailing slashes are dropped so `/v1/auth/` and `/v1/auth` are the same prefix
+/// - entries without a path are skipped, a blank spec is `None`
+pub fn parse_prefix_list(value: &str) -> Vec<(String, Option<String>)> {
+    value
+        .split([',', '\n'])
+        .filter_map(|entry| {
+            let (path, spec) = match entry.split_once('=') {
+                Some((path, spec)) => (path, Some(spec.trim())),
+                None => (entry, None),
+            };
+            let path = path.trim().trim_end_matches('/');
+            if path.is_empty() {
+                return None;
+            }
+            let spec = spec.filter(|spec| !spec.is_empty()).map(String::from);
+            Some((path.to_string(), spec))
+        })
+        .collect()
+}
+
+/// a path prefix only covers whole path segments
+/// so `/v1/auth` covers `/v1/auth/login` but not `/v1/authorize`
+pub fn covers_path(prefix: &str, path: &str) -> bool {
+    path.strip_prefix(prefix)
+        .map(|rest| rest.is_empty() || rest.starts_with('/'))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_list_should_parse_paths_and_specs() {
+        assert_eq!(
+            parse_prefix_list(" /v1/auth/ = paused ,/v1/oauth2\n/v1/health=,,=no path"),
+            vec![
+                (String::from("/v1/auth"), Some(String::from("paused"))),
+                (String::from("/v1/oauth2"), None),
+                (String::from("/v1/health"), None),
+            ]
+        );
+        assert!(parse_prefix_list("").is_empty());
+    }
+
+    #[test]
+    fn flags_should_accept_common_spellings() {
+        for value in ["true", "TRUE", "1", "yes", "On"] {
+            assert_eq!(parse_flag(value), Some(true), "{value}");
+        }
+        for value in ["false", "False", "0", "no", "OFF"] {
+            assert_eq!(parse_flag(value), Some(false), "{value}");
+        }
+        assert_eq!(parse_flag("enabled"), None);
+    }
+
+    #[test]
+    fn unparsable_values_should_be_ignored() {
+        env::set_var("ENV_CONFIG_TEST_BACKLOG", " not a number ");
+        env::set_var("ENV_CONFIG_TEST_FLAG", " 0 ");
+
+        assert_eq!(read_env::<i32>("ENV_CONFIG_TEST_BACKLOG"), None);
+        assert_eq!(read_env_flag("ENV_CONFIG_TEST_FLAG"), Some(false));
+        assert_eq!(read_env::<i32>("ENV_CONFIG_TEST_MISSING"), None);
+    }
+}
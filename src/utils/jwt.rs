@@ -2,12 +2,14 @@ use crate::utils::api_response::ApiErrorResponse as AuthError;
 use axum::async_trait;
 use axum::extract::{FromRequest, RequestParts, TypedHeader};
 use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::Method;
 use jsonwebtoken::encode;
 use jsonwebtoken::{decode, Algorithm};
 use jsonwebtoken::{DecodingKey, EncodingKey};
 use jsonwebtoken::{Header, Validation};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Add;
 use std::time::SystemTime;
@@ -26,6 +28,10 @@ pub struct JwtClaims {
     pub email: String,
     pub fullname: String,
     pub exp: u64,
+    /// read-only tokens may only be used with non-mutating HTTP methods
+    /// tokens minted before the claim existed are treated as read-write
+    #[serde(default)]
+    pub read_only: bool,
 }
 
 impl JwtClaims {
@@ -39,6 +45,7 @@ impl JwtClaims {
     //      email: String::from("cout@lahpev.mg"),
     //    fullname: String::from("Jesse Rodney"),
     //  exp: expiration_time,
+    //  read_only: false,
     ///};
     ///let token = sample_claim.generate_token();
     ///let token: String = token.unwrap();
@@ -52,6 +59,12 @@ impl JwtClaims {
         //build the user jwt token
         encode(&jwt_header, &self, &JWT_SECRET.encoding).ok()
     }
+
+    /// check if the claims may be used for a request with the given method
+    /// read-only claims are limited to GET, HEAD and OPTIONS
+    pub fn permits(&self, method: &Method) -> bool {
+        !self.read_only || matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+    }
 }
 
 #[async_trait]
@@ -88,6 +101,13 @@ where
             .map_err(|err| AuthError::InvalidToken {
                 message: err.to_string(),
             })?;
+
+        // reject mutating requests made with a read-only token
+        if !token_data.claims.permits(req.method()) {
+            return Err(AuthError::Forbidden {
+                message: String::from("This token is read-only and cannot be used to modify data"),
+            });
+        }
         Ok(token_data.claims)
     }
 }
@@ -97,8 +117,8 @@ impl Display for JwtClaims {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "id: {}\nemail: {}\nfullname: {}\nexp:{}",
-            self.id, self.email, self.fullname, self.exp
+            "id: {}\nemail: {}\nfullname: {}\nexp:{}\nread_only: {}",
+            self.id, self.email, self.fullname, self.exp, self.read_only
         )
     }
 }
@@ -124,7 +144,25 @@ impl JwtEncryptionKeys {
 #[serde(rename_all = "camelCase")]
 pub struct JwtPayload {
     pub token: String,
-    pub token_type: String,
+    pub token_type: Cow<'static, str>,
+}
+
+impl JwtPayload {
+    /// an access token payload, the token type is borrowed so it is not allocated per request
+    pub fn bearer(token: String) -> Self {
+        Self {
+            token,
+            token_type: Cow::Borrowed("Bearer"),
+        }
+    }
+
+    /// a refresh token payload
+    pub fn refresh(token: String) -> Self {
+        Self {
+            token,
+            token_type: Cow::Borrowed("Refresh"),
+        }
+    }
 }
 
 /// set the expiration of token
@@ -163,6 +201,7 @@ mod tests {
             email: String::from("cout@lahpev.mg"),
             fullname: String::from("Jesse Rodney"),
             exp: expiration_time,
+            read_only: false,
         };
         let token = sample_claim.generate_token();
         // let token: String = token.unwrap();
@@ -190,4 +229,37 @@ mod tests {
 
         assert_eq!(_set_jwt_exp(now, exp), expected);
     }
+
+    #[test]
+    fn read_only_claims_should_only_permit_safe_methods() {
+        let claims = JwtClaims {
+            id: String::from("16260b1d-1554-5b6f-a221-56ff4b34199c"),
+            email: String::from("cout@lahpev.mg"),
+            fullname: String::from("Jesse Rodney"),
+            exp: 0,
+            read_only: true,
+        };
+
+        assert!(claims.permits(&Method::GET));
+        assert!(claims.permits(&Method::HEAD));
+        assert!(!claims.permits(&Method::POST));
+        assert!(!claims.permits(&Method::PUT));
+        assert!(!claims.permits(&Method::DELETE));
+
+        let claims = JwtClaims {
+            read_only: false,
+            ..claims
+        };
+        assert!(claims.permits(&Method::PUT));
+    }
+
+    #[test]
+    fn claims_without_read_only_field_should_default_to_read_write() {
+        let claims: JwtClaims = serde_json::from_str(
+            r#"{"id":"1","email":"cout@lahpev.mg","fullname":"Jesse Rodney","exp":0}"#,
+        )
+        .unwrap();
+
+        assert!(!claims.read_only);
+    }
 }
@@ -0,0 +1,222 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::utils::api_response::ApiErrorResponse;
+use crate::utils::env_config::{covers_path, parse_prefix_list, read_env};
+
+/// endpoints that have been switched off without a deploy
+/// the switches are read from DISABLED_ENDPOINTS, a comma separated list of path prefixes,
+/// each one optionally followed by `=` and the reason returned to the client e.g
+/// DISABLED_ENDPOINTS=/v1/auth/sign-up=sign up is paused for maintenance,/v1/oauth2
+///
+/// switches can also be flipped at runtime, see [`spawn_reloader`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KillSwitches {
+    disabled: Vec<(String, Option<String>)>,
+}
+
+impl KillSwitches {
+    /// read the kill switches from the environment, nothing is disabled if the variable is not set
+    pub fn from_env() -> Self {
+        Self::parse(&read_env::<String>("DISABLED_ENDPOINTS").unwrap_or_default())
+    }
+
+    /// the reason returned to the client is the spec after `=`, see [`parse_prefix_list`]
+    fn parse(value: &str) -> Self {
+        Self {
+            disabled: parse_prefix_list(value),
+        }
+    }
+
+    /// find the switch covering the path
+    fn find(&self, path: &str) -> Option<&(String, Option<String>)> {
+        self.disabled
+            .iter()
+            .find(|(prefix, _)| covers_path(prefix, path))
+    }
+}
+
+/// the kill switches read by the middleware and replaced by the reloader
+/// shared with the middleware as an axum extension
+#[derive(Debug, Default, Clone)]
+pub struct SharedKillSwitches(Arc<RwLock<KillSwitches>>);
+
+impl SharedKillSwitches {
+    pub fn new(kill_switches: KillSwitches) -> Self {
+        Self(Arc::new(RwLock::new(kill_switches)))
+    }
+
+    /// swap in the new switches, returns true if they changed
+    pub fn replace(&self, kill_switches: KillSwitches) -> bool {
+        // the switches are replaced as a whole, they are still valid if a holder of the lock panicked
+        let mut current = self
+            .0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *current == kill_switches {
+            return false;
+        }
+        *current = kill_switches;
+        true
+    }
+
+    /// the message returned to the client if the path has been disabled
+    fn rejection(&self, path: &str) -> Option<String> {
+        let kill_switches = self
+            .0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        kill_switches.find(path).map(|(_, reason)| match reason {
+            Some(reason) => format!("This endpoint is temporarily disabled: {reason}"),
+            None => String::from("This endpoint is temporarily disabled"),
+        })
+    }
+}
+
+/// reload the kill switches while the server is running
+/// 1. KILL_SWITCH_FILE - a file in the DISABLED_ENDPOINTS format, switches may also be on their own line.
+///    The switches in the file are added to the ones in DISABLED_ENDPOINTS, a missing file disables nothing
+/// 2. KILL_SWITCH_RELOAD_SECS - how often the file is read, defaults to 10 seconds
+///
+/// nothing is reloaded if KILL_SWITCH_FILE is not set
+pub fn spawn_reloader(kill_switches: SharedKillSwitches) {
+    let file = match read_env::<PathBuf>("KILL_SWITCH_FILE") {
+        Some(file) => file,
+        None => return,
+    };
+    let reload_interval = Duration::from_secs(
+        read_env::<u64>("KILL_SWITCH_RELOAD_SECS")
+            .unwrap_or(10)
+            .max(1),
+    );
+    let from_env = KillSwitches::from_env();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(reload_interval);
+        loop {
+            interval.tick().await;
+            let from_file = match tokio::fs::read_to_string(&file).await {
+                Ok(contents) => KillSwitches::parse(&contents),
+                Err(error) if error.kind() == ErrorKind::NotFound => KillSwitches::default(),
+                Err(error) => {
+                    // keep the current switches rather than turning them all off
+                    tracing::error!(
+                        "could not read the kill switch file {}: {error}",
+                        file.display()
+                    );
+                    continue;
+                }
+            };
+
+            let mut reloaded = from_env.clone();
+            reloaded.disabled.extend(from_file.disabled);
+            if kill_switches.replace(reloaded.clone()) {
+                tracing::warn!("kill switches reloaded, disabled {:?}", reloaded.disabled);
+            }
+        }
+    });
+}
+
+/// reject requests to disabled endpoints with a 503 and the reason the endpoint was disabled
+/// the middleware has to be layered inside `negotiate_envelope` so the rejection is negotiated too,
+/// and inside the [`SharedKillSwitches`] extension it reads the switches from
+pub async fn reject_disabled_endpoints<B>(
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiErrorResponse> {
+    let rejection = req
+        .extensions()
+        .get::<SharedKillSwitches>()
+        .and_then(|kill_switches| kill_switches.rejection(req.uri().path()));
+    if let Some(message) = rejection {
+        return Err(ApiErrorResponse::ServiceUnavailable { message });
+    }
+
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{middleware, Extension, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn kill_switches_should_parse_paths_and_reasons() {
+        let kill_switches =
+            KillSwitches::parse(" /v1/auth/sign-up/ = sign up is paused ,/v1/oauth2,,=no path");
+
+        assert_eq!(
+            kill_switches.disabled,
+            vec![
+                (
+                    String::from("/v1/auth/sign-up"),
+                    Some(String::from("sign up is paused"))
+                ),
+                (String::from("/v1/oauth2"), None),
+            ]
+        );
+        assert_eq!(KillSwitches::parse(""), KillSwitches::default());
+    }
+
+    #[test]
+    fn kill_switches_should_only_match_whole_path_segments() {
+        let kill_switches = KillSwitches::parse("/v1/auth");
+
+        assert!(kill_switches.find("/v1/auth").is_some());
+        assert!(kill_switches.find("/v1/auth/login").is_some());
+        assert!(kill_switches.find("/v1/authorize").is_none());
+        assert!(kill_switches.find("/v1/health/database").is_none());
+    }
+
+    #[tokio::test]
+    async fn disabled_endpoints_should_be_rejected_until_switched_back_on() {
+        let kill_switches =
+            SharedKillSwitches::new(KillSwitches::parse("/v1/auth/sign-up=sign up is paused"));
+        let app = Router::new()
+            .route("/v1/auth/sign-up", get(|| async { "signed up" }))
+            .layer(middleware::from_fn(reject_disabled_endpoints))
+            .layer(Extension(kill_switches.clone()));
+        let request = || {
+            Request::builder()
+                .uri("/v1/auth/sign-up")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["message"],
+            "This endpoint is temporarily disabled: sign up is paused"
+        );
+
+        assert!(kill_switches.replace(KillSwitches::default()));
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// the operations that return a success response
+/// each operation has a stable, machine readable code and a human readable message
+/// the code is sent alongside the message so clients can match on it or translate it
+/// messages are English only, the server does not read Accept-Language
+///
+/// # Example
+/// ```rust
+/// let response = ApiSuccessResponse::new(Operation::Login, Some(jwt_payload));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    SignUp,
+    VerifyEmail,
+    RequestNewOtp,
+    RequestAccountVerification,
+    Login,
+    FetchUserProfile,
+    RequestPasswordReset,
+    ResetPassword,
+    UpdateUserProfile,
+    RefreshToken,
+    CreateReadOnlyToken,
+    FetchDatabaseHealth,
+    FetchBuildInformation,
+}
+
+impl Operation {
+    /// the stable message code, it must not change once released
+    pub fn code(&self) -> &'static str {
+        match self {
+            Operation::SignUp => "auth.sign_up",
+            Operation::VerifyEmail => "auth.verify_email",
+            Operation::RequestNewOtp => "auth.request_new_otp",
+            Operation::RequestAccountVerification => "auth.request_account_verification",
+            Operation::Login => "auth.login",
+            Operation::FetchUserProfile => "auth.fetch_user_profile",
+            Operation::RequestPasswordReset => "auth.request_password_reset",
+            Operation::ResetPassword => "auth.reset_password",
+            Operation::UpdateUserProfile => "auth.update_user_profile",
+            Operation::RefreshToken => "auth.refresh_token",
+            Operation::CreateReadOnlyToken => "auth.create_read_only_token",
+            Operation::FetchDatabaseHealth => "health.database",
+            Operation::FetchBuildInformation => "health.version",
+        }
+    }
+
+    /// the English message
+    pub fn message(&self) -> &'static str {
+        match self {
+            Operation::SignUp
+            | Operation::RequestNewOtp
+            | Operation::RequestAccountVerification => {
+                "Please verify OTP sent to your email to continue"
+            }
+            Operation::VerifyEmail => "User account successfully activated",
+            Operation::Login => "User successfully logged in",
+            Operation::FetchUserProfile => "User information successfully fetched",
+            Operation::RequestPasswordReset => "Please use the token to reset your password",
+            Operation::ResetPassword => "User password successfully updated",
+            Operation::UpdateUserProfile => "User information successfully updated",
+            Operation::RefreshToken => "User authorization token successfully refreshed",
+            Operation::CreateReadOnlyToken => "Read-only token successfully created",
+            Operation::FetchDatabaseHealth => "Database pool metrics successfully fetched",
+            Operation::FetchBuildInformation => "Build information successfully fetched",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// every operation, a new one has to be added here and to `must_be_listed`
+    const OPERATIONS: &[Operation] = &[
+        Operation::SignUp,
+        Operation::VerifyEmail,
+        Operation::RequestNewOtp,
+        Operation::RequestAccountVerification,
+        Operation::Login,
+        Operation::FetchUserProfile,
+        Operation::RequestPasswordReset,
+        Operation::ResetPassword,
+        Operation::UpdateUserProfile,
+        Operation::RefreshToken,
+        Operation::CreateReadOnlyToken,
+        Operation::FetchDatabaseHealth,
+        Operation::FetchBuildInformation,
+    ];
+
+    /// the match stops compiling when an operation is added, as a reminder to list it
+    fn must_be_listed(operation: Operation) {
+        match operation {
+            Operation::SignUp
+            | Operation::VerifyEmail
+            | Operation::RequestNewOtp
+            | Operation::RequestAccountVerification
+            | Operation::Login
+            | Operation::FetchUserProfile
+            | Operation::RequestPasswordReset
+            | Operation::ResetPassword
+            | Operation::UpdateUserProfile
+            | Operation::RefreshToken
+            | Operation::CreateReadOnlyToken
+            | Operation::FetchDatabaseHealth
+            | Operation::FetchBuildInformation => {}
+        }
+    }
+
+    #[test]
+    fn message_codes_should_be_unique() {
+        OPERATIONS.iter().copied().for_each(must_be_listed);
+
+        let codes: HashSet<&str> = OPERATIONS.iter().map(Operation::code).collect();
+        assert_eq!(codes.len(), OPERATIONS.len());
+    }
+}
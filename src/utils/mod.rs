@@ -1,5 +1,15 @@
+#[cfg(test)]
+pub mod alloc_counter;
 pub mod api_response;
+pub mod build_info;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod env_config;
 pub mod jwt;
+pub mod kill_switch;
+pub mod message_catalog;
 pub mod message_queue;
 pub mod otp_handler;
+pub mod pool_monitor;
+pub mod server_config;
 pub mod sql_query_builder;
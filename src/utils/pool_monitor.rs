@@ -0,0 +1,360 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::utils::env_config::read_env;
+
+///the database pool monitor configuration
+/// 1. POOL_MONITOR_INTERVAL_SECS - how often the pool is probed, defaults to 5 seconds
+/// 2. POOL_ALERT_THRESHOLD_MS - the acquisition latency considered slow, defaults to 500 milliseconds
+/// 3. POOL_ALERT_AFTER_SECS - how long acquisition must stay slow before alerting, defaults to 30 seconds
+/// 4. POOL_ALERT_WEBHOOK_URL - an optional URL the alert is posted to, alerts are always logged
+/// 5. POOL_METRICS_WINDOW_SECS - the window the maximum probe wait is taken over, defaults to 300 seconds
+#[derive(Debug, Clone)]
+pub struct PoolMonitorConfig {
+    pub interval: Duration,
+    pub alert_threshold: Duration,
+    pub alert_after: Duration,
+    pub alert_webhook_url: Option<String>,
+    pub metrics_window: Duration,
+}
+
+impl PoolMonitorConfig {
+    /// read the configuration from the environment, falling back to the defaults
+    pub fn from_env() -> Self {
+        let read_u64 = |key: &str, default: u64| read_env(key).unwrap_or(default);
+
+        Self {
+            interval: Duration::from_secs(read_u64("POOL_MONITOR_INTERVAL_SECS", 5).max(1)),
+            alert_threshold: Duration::from_millis(read_u64("POOL_ALERT_THRESHOLD_MS", 500)),
+            alert_after: Duration::from_secs(read_u64("POOL_ALERT_AFTER_SECS", 30)),
+            alert_webhook_url: read_env("POOL_ALERT_WEBHOOK_URL"),
+            metrics_window: Duration::from_secs(read_u64("POOL_METRICS_WINDOW_SECS", 300).max(1)),
+        }
+    }
+}
+
+/// database pool metrics collected by the monitor
+/// the metrics are shared with the health handler as an axum extension
+///
+/// the wait and failure metrics only measure the monitor's own probe, one checkout per interval,
+/// the checkouts made by the request handlers are not timed, they are only counted when they time out.
+/// The probe is a real checkout, so while the pool is starved it queues behind the handlers
+/// and, once served, briefly holds one of the connections
+#[derive(Debug, Default)]
+pub struct PoolMetrics {
+    max_connections: u32,
+    window: Duration,
+    probe_wait_ms: AtomicU64,
+    /// the probe waits within the window, oldest first
+    recent_probe_waits: Mutex<VecDeque<(Instant, u64)>>,
+    probe_failures: AtomicU64,
+    probes: AtomicU64,
+    last_probe_failed: AtomicBool,
+}
+
+/// a point in time view of the pool metrics, returned by the health endpoint
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolMetricsSnapshot {
+    pub healthy: bool,
+    pub size: u32,
+    pub idle: usize,
+    pub in_use: u32,
+    pub max_connections: u32,
+    /// the share of `max_connections` currently checked out, from 0 to 1
+    pub saturation: f64,
+    /// how long the last probe waited for a connection
+    pub probe_wait_ms: u64,
+    /// the longest probe wait within the metrics window
+    pub max_probe_wait_ms: u64,
+    pub probe_failures: u64,
+    pub probes: u64,
+    /// the request handler checkouts that timed out waiting for a connection, since startup
+    pub checkout_timeouts: u64,
+}
+
+impl PoolMetrics {
+    /// `window` is how far back the maximum probe wait looks
+    pub fn new(max_connections: u32, window: Duration) -> Self {
+        Self {
+            max_connections,
+            window,
+            ..Default::default()
+        }
+    }
+
+    /// record a successful probe
+    pub fn record_probe(&self, wait: Duration, now: Instant) {
+        let wait_ms = u64::try_from(wait.as_millis()).unwrap_or(u64::MAX);
+        self.probe_wait_ms.store(wait_ms, Ordering::Relaxed);
+        let mut recent_probe_waits = self.recent_probe_waits();
+        recent_probe_waits.push_back((now, wait_ms));
+        self.prune(&mut recent_probe_waits, now);
+        self.probes.fetch_add(1, Ordering::Relaxed);
+        self.last_probe_failed.store(false, Ordering::Relaxed);
+    }
+
+    /// record a failed or timed out probe
+    pub fn record_probe_failure(&self) {
+        self.probe_failures.fetch_add(1, Ordering::Relaxed);
+        self.probes.fetch_add(1, Ordering::Relaxed);
+        self.last_probe_failed.store(true, Ordering::Relaxed);
+    }
+
+    /// the longest probe wait within the window ending at `now`
+    fn max_probe_wait_ms(&self, now: Instant) -> u64 {
+        let mut recent_probe_waits = self.recent_probe_waits();
+        self.prune(&mut recent_probe_waits, now);
+        recent_probe_waits
+            .iter()
+            .map(|(_, wait_ms)| *wait_ms)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn recent_probe_waits(&self) -> std::sync::MutexGuard<'_, VecDeque<(Instant, u64)>> {
+        // the waits are plain numbers, they are still valid if a holder of the lock panicked
+        self.recent_probe_waits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// drop the waits older than the window
+    fn prune(&self, recent_probe_waits: &mut VecDeque<(Instant, u64)>, now: Instant) {
+        while let Some((probed_at, _)) = recent_probe_waits.front() {
+            if now.saturating_duration_since(*probed_at) <= self.window {
+                break;
+            }
+            recent_probe_waits.pop_front();
+        }
+    }
+
+    /// combine the recorded metrics with the current state of the pool
+    pub fn snapshot(&self, pool: &PgPool) -> PoolMetricsSnapshot {
+        let size = pool.size();
+        let idle = pool.num_idle();
+        let in_use = size.saturating_sub(u32::try_from(idle).unwrap_or(u32::MAX));
+        let saturation = if self.max_connections == 0 {
+            0.0
+        } else {
+            f64::from(in_use) / f64::from(self.max_connections)
+        };
+
+        PoolMetricsSnapshot {
+            healthy: !pool.is_closed() && !self.last_probe_failed.load(Ordering::Relaxed),
+            size,
+            idle,
+            in_use,
+            max_connections: self.max_connections,
+            saturation,
+            probe_wait_ms: self.probe_wait_ms.load(Ordering::Relaxed),
+            max_probe_wait_ms: self.max_probe_wait_ms(Instant::now()),
+            probe_failures: self.probe_failures.load(Ordering::Relaxed),
+            probes: self.probes.load(Ordering::Relaxed),
+            checkout_timeouts: CHECKOUT_TIMEOUTS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// the handler checkouts that timed out, counted where database errors become API errors
+/// a process wide counter because the conversion has no access to the request extensions
+static CHECKOUT_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+
+/// count a request handler checkout that timed out waiting for a connection
+pub fn record_checkout_timeout() {
+    CHECKOUT_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// keeps track of how long acquisition has been slow
+/// an alert is raised once per slow period, the period ends when a probe is fast again
+#[derive(Debug, Default)]
+struct AlertState {
+    slow_since: Option<Instant>,
+    alerted: bool,
+}
+
+impl AlertState {
+    /// observe a probe result, `None` is a failed probe. Returns true if an alert should be raised
+    fn observe(
+        &mut self,
+        wait: Option<Duration>,
+        now: Instant,
+        config: &PoolMonitorConfig,
+    ) -> bool {
+        let is_slow = !matches!(wait, Some(wait) if wait < config.alert_threshold);
+        if !is_slow {
+            *self = Self::default();
+            return false;
+        }
+
+        let slow_since = *self.slow_since.get_or_insert(now);
+        if self.alerted || now.duration_since(slow_since) < config.alert_after {
+            return false;
+        }
+        self.alerted = true;
+        true
+    }
+}
+
+/// start the pool monitor in the background
+/// the monitor probes the pool with a checkout on every interval, records how long the probe waited
+/// and raises an alert when the wait stays above the threshold
+pub fn spawn(pool: PgPool, metrics: Arc<PoolMetrics>, config: PoolMonitorConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        let mut alert_state = AlertState::default();
+        let webhook_client = reqwest::Client::builder()
+            .timeout(ALERT_WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        loop {
+            interval.tick().await;
+            let started_at = Instant::now();
+            // don't wait on the probe for longer than the alert threshold and the interval together
+            let probe =
+                tokio::time::timeout(config.alert_threshold + config.interval, pool.acquire())
+                    .await;
+
+            let wait = match probe {
+                // the connection goes back to the pool at the end of the arm
+                Ok(Ok(_connection)) => {
+                    let wait = started_at.elapsed();
+                    metrics.record_probe(wait, Instant::now());
+                    Some(wait)
+                }
+                _ => {
+                    metrics.record_probe_failure();
+                    None
+                }
+            };
+
+            if alert_state.observe(wait, Instant::now(), &config) {
+                alert(&metrics.snapshot(&pool), &config, &webhook_client);
+            }
+        }
+    });
+}
+
+/// how long the webhook may take to accept an alert
+const ALERT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// log the alert and post it to the webhook if one is configured
+/// the post runs in its own task so a slow webhook never holds up the probes
+fn alert(
+    snapshot: &PoolMetricsSnapshot,
+    config: &PoolMonitorConfig,
+    webhook_client: &reqwest::Client,
+) {
+    tracing::error!(
+        "the database pool probe has waited longer than {:?} for {:?}, last probe wait {}ms, saturation {:.2}, {} probe failures, {} checkout timeouts",
+        config.alert_threshold,
+        config.alert_after,
+        snapshot.probe_wait_ms,
+        snapshot.saturation,
+        snapshot.probe_failures,
+        snapshot.checkout_timeouts,
+    );
+
+    if let Some(webhook_url) = &config.alert_webhook_url {
+        let body = json!({
+            "alert": "database_pool_starvation",
+            "metrics": snapshot,
+        });
+        let request = webhook_client.post(webhook_url).json(&body);
+        tokio::spawn(async move {
+            if let Err(error) = request.send().await {
+                tracing::error!("could not send the database pool alert: {error}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PoolMonitorConfig {
+        PoolMonitorConfig {
+            interval: Duration::from_secs(5),
+            alert_threshold: Duration::from_millis(500),
+            alert_after: Duration::from_secs(30),
+            alert_webhook_url: None,
+            metrics_window: Duration::from_secs(300),
+        }
+    }
+
+    #[test]
+    fn alert_should_be_raised_once_after_latency_stays_slow() {
+        let config = config();
+        let mut alert_state = AlertState::default();
+        let started_at = Instant::now();
+        let slow = Some(Duration::from_millis(900));
+
+        assert!(!alert_state.observe(slow, started_at, &config));
+        assert!(!alert_state.observe(slow, started_at + Duration::from_secs(20), &config));
+        assert!(alert_state.observe(None, started_at + Duration::from_secs(30), &config));
+        assert!(!alert_state.observe(slow, started_at + Duration::from_secs(35), &config));
+    }
+
+    #[test]
+    fn alert_state_should_reset_when_latency_recovers() {
+        let config = config();
+        let mut alert_state = AlertState::default();
+        let started_at = Instant::now();
+        let slow = Some(Duration::from_millis(900));
+        let fast = Some(Duration::from_millis(3));
+
+        assert!(!alert_state.observe(slow, started_at, &config));
+        assert!(!alert_state.observe(fast, started_at + Duration::from_secs(20), &config));
+        assert!(!alert_state.observe(slow, started_at + Duration::from_secs(35), &config));
+        assert!(alert_state.observe(slow, started_at + Duration::from_secs(65), &config));
+    }
+
+    #[test]
+    fn metrics_should_track_the_maximum_wait_within_the_window() {
+        let metrics = PoolMetrics::new(5, Duration::from_secs(60));
+        let started_at = Instant::now();
+        metrics.record_probe(Duration::from_millis(40), started_at);
+        metrics.record_probe(
+            Duration::from_millis(10),
+            started_at + Duration::from_secs(30),
+        );
+        metrics.record_probe_failure();
+
+        assert_eq!(metrics.probe_wait_ms.load(Ordering::Relaxed), 10);
+        assert_eq!(
+            metrics.max_probe_wait_ms(started_at + Duration::from_secs(30)),
+            40
+        );
+        assert_eq!(
+            metrics.max_probe_wait_ms(started_at + Duration::from_secs(61)),
+            10
+        );
+        assert_eq!(
+            metrics.max_probe_wait_ms(started_at + Duration::from_secs(91)),
+            0
+        );
+        assert_eq!(metrics.probe_failures.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.probes.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2022 The raccoon Authors. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hyper::server::conn::AddrIncoming;
+use hyper::server::Builder;
+use socket2::{Domain, Socket, Type};
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use crate::utils::env_config::{read_env, read_env_flag};
+
+/// the HTTP server tuning options, the defaults match hyper's except for the TCP backlog
+/// 1. HTTP2_ENABLED - serve HTTP/2 alongside HTTP/1.1, defaults to true. Flags also accept 1/0, yes/no and on/off
+/// 2. HTTP2_MAX_CONCURRENT_STREAMS - the concurrent streams allowed per HTTP/2 connection, unlimited if not set
+/// 3. HTTP2_KEEP_ALIVE_INTERVAL_SECS - how often HTTP/2 pings are sent, no pings if not set
+/// 4. HTTP2_KEEP_ALIVE_TIMEOUT_SECS - how long to wait for a ping to be acknowledged, defaults to 20 seconds
+/// 5. HTTP1_KEEP_ALIVE - reuse HTTP/1.1 connections, defaults to true
+/// 6. TCP_KEEP_ALIVE_SECS - the idle time before TCP keep-alive probes are sent, no probes if not set
+/// 7. TCP_BACKLOG - the length of the pending connections queue, defaults to 1024
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerTuning {
+    pub http2_enabled: bool,
+    pub http2_max_concurrent_streams: Option<u32>,
+    pub http2_keep_alive_interval: Option<Duration>,
+    pub http2_keep_alive_timeout: Duration,
+    pub http1_keep_alive: bool,
+    pub tcp_keep_alive: Option<Duration>,
+    pub tcp_backlog: i32,
+}
+
+impl Default for ServerTuning {
+    fn default() -> Self {
+        Self {
+            http2_enabled: true,
+            http2_max_concurrent_streams: None,
+            http2_keep_alive_interval: None,
+            http2_keep_alive_timeout: Duration::from_secs(20),
+            http1_keep_alive: true,
+            tcp_keep_alive: None,
+            tcp_backlog: 1024,
+        }
+    }
+}
+
+impl ServerTuning {
+    /// read the tuning options from the environment, falling back to the defaults
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read_secs = |key: &str| read_env::<u64>(key).map(Duration::from_secs);
+
+        Self {
+            http2_enabled: read_env_flag("HTTP2_ENABLED").unwrap_or(defaults.http2_enabled),
+            http2_max_concurrent_streams: read_env("HTTP2_MAX_CONCURRENT_STREAMS"),
+            http2_keep_alive_interval: read_secs("HTTP2_KEEP_ALIVE_INTERVAL_SECS"),
+            http2_keep_alive_timeout: read_secs("HTTP2_KEEP_ALIVE_TIMEOUT_SECS")
+                .unwrap_or(defaults.http2_keep_alive_timeout),
+            http1_keep_alive: read_env_flag("HTTP1_KEEP_ALIVE")
+                .unwrap_or(defaults.http1_keep_alive),
+            tcp_keep_alive: read_secs("TCP_KEEP_ALIVE_SECS"),
+            tcp_backlog: read_env::<i32>("TCP_BACKLOG")
+                .filter(|backlog| *backlog > 0)
+                .unwrap_or(defaults.tcp_backlog),
+        }
+    }
+
+    /// bind the address with the configured backlog and return the tuned server builder
+    pub fn bind(&self, address: &SocketAddr) -> std::io::Result<Builder<AddrIncoming>> {
+        let listener = self.listen(address)?;
+        let builder = axum::Server::from_tcp(listener)
+            .map_err(std::io::Error::other)?
+            .tcp_keepalive(self.tcp_keep_alive)
+            .http1_keepalive(self.http1_keep_alive)
+            .http1_only(!self.http2_enabled)
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams)
+            .http2_keep_alive_interval(self.http2_keep_alive_interval)
+            .http2_keep_alive_timeout(self.http2_keep_alive_timeout);
+
+        Ok(builder)
+    }
+
+    /// the standard library listener does not expose the backlog, so the socket is built by hand
+    fn listen(&self, address: &SocketAddr) -> std::io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(*address), Type::STREAM, None)?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&(*address).into())?;
+        socket.listen(self.tcp_backlog)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listener_should_bind_with_the_configured_backlog() {
+        let server_tuning = ServerTuning {
+            tcp_backlog: 16,
+            ..Default::default()
+        };
+        let listener = server_tuning
+            .listen(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .unwrap();
+
+        assert_ne!(listener.local_addr().unwrap().port(), 0);
+    }
+}
@@ -27,6 +27,7 @@ pub trait Create {
 
 /// delete model record
 #[async_trait]
+#[allow(dead_code)]
 pub trait DeleteEntity {
     type Entity;
     type Attributes;
@@ -48,6 +49,7 @@ pub trait Find {
 
 /// find user, create if not exist;
 #[async_trait]
+#[allow(dead_code)]
 pub trait FindOrCreate {
     type Entity;
     type Attributes;
@@ -58,6 +60,7 @@ pub trait FindOrCreate {
     ) -> Result<Self::Entity, sqlx::Error>;
 }
 #[async_trait]
+#[allow(dead_code)]
 pub trait FindAndCount {
     type Entity;
     type Attributes;
@@ -70,6 +73,7 @@ pub trait FindAndCount {
 
 /// update fields
 #[async_trait]
+#[allow(dead_code)]
 pub trait UpdateEntity {
     type Entity;
     async fn update(